@@ -20,14 +20,34 @@ const ROT: u32 = 23;
 ///
 #[derive(Debug, Clone)]
 pub struct AHasher {
-    buffer: u64
+    buffer: u64,
+    /// Staging area for `write_streaming`: bytes that have been fed in but don't yet
+    /// fill a whole 8-byte block. Unused, and left zeroed, by the one-shot `write` path.
+    carry: [u8; 8],
+    /// How many of the leading bytes of `carry` are valid.
+    carry_len: u8,
+    /// The rolling key used by `write_streaming`'s `ordered_update` calls, mirroring
+    /// the local `key` variable the one-shot `write` keeps on its stack.
+    stream_key: u64,
+    /// Running total of bytes passed to `write_streaming` across every call, folded
+    /// into the buffer at `finish` time the same way the one-shot path folds `data.len()`.
+    stream_len: u64,
 }
 
 impl AHasher {
     /// Creates a new hasher keyed to the provided keys.
     #[inline]
     pub(crate) fn new_with_keys(key0: u64, key1: u64) -> AHasher {
-        AHasher { buffer: key0 ^ key1.rotate_left(ROT) }
+        let buffer = key0 ^ key1.rotate_left(ROT);
+        AHasher { buffer, carry: [0; 8], carry_len: 0, stream_key: buffer, stream_len: 0 }
+    }
+
+    /// Creates a hasher the same way as `new_with_keys`, but under a name whose output
+    /// format is frozen forever, unlike `new_with_keys` which may change shape between
+    /// releases. See the `compatibility` test module for the pinned vectors.
+    #[inline]
+    pub fn stable_with_keys(key0: u64, key1: u64) -> AHasher {
+        Self::new_with_keys(key0, key1)
     }
 
     /// This update function has the goal of updating the buffer with a single multiply
@@ -66,6 +86,82 @@ impl AHasher {
         self.buffer ^= (new_data ^ key).wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE);
         key.wrapping_add(INCREMENT)
     }
+
+    /// Finishes the hash into a full 128 bits. The low 64 bits equal `finish()`; the
+    /// high 64 bits come from a second, decorrelated finalization round.
+    #[inline]
+    pub fn finish128(&self) -> u128 {
+        let buffer = self.pre_finalize_buffer();
+        let low = buffer.wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE);
+        let high = (buffer.rotate_left(ROT) ^ INCREMENT)
+            .wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE);
+        (low as u128) | ((high as u128) << 64)
+    }
+
+    /// Feeds bytes into the hasher incrementally, producing output that depends only
+    /// on the concatenation of all bytes fed across every call, so
+    /// `h.write_streaming(b"abc"); h.write_streaming(b"de")` and a single
+    /// `h.write_streaming(b"abcde")` reach `finish()` the same way. This differs from
+    /// `Hasher::write`, which folds `data.len()` into the buffer on every call and
+    /// therefore treats the same bytes arriving in different-sized chunks as different
+    /// input; that makes plain `write` a poor fit for data arriving from a reader or
+    /// network stream where the chunk boundaries aren't meaningful. Prefer the plain
+    /// `write` path when the whole input is available up front, since it doesn't need
+    /// to buffer a partial block.
+    ///
+    /// `write_streaming` keeps its length and partial block in separate fields (the
+    /// carry buffer and running length) while `write` folds its length directly into
+    /// `buffer`, yet both mix their blocks into the same `buffer`, so don't mix calls
+    /// to both on the same hasher instance.
+    #[inline]
+    pub fn write_streaming(&mut self, mut input: &[u8]) {
+        self.stream_len = self.stream_len.wrapping_add(input.len() as u64);
+        if self.carry_len > 0 {
+            let space = 8 - self.carry_len as usize;
+            let take = space.min(input.len());
+            let start = self.carry_len as usize;
+            self.carry[start..start + take].copy_from_slice(&input[..take]);
+            self.carry_len += take as u8;
+            input = &input[take..];
+            if self.carry_len as usize == 8 {
+                let val: u64 = self.carry.convert();
+                self.stream_key = self.ordered_update(val, self.stream_key);
+                self.carry_len = 0;
+            }
+        }
+        while input.len() >= 8 {
+            let (block, rest) = input.split_at(8);
+            let val: u64 = as_array!(block, 8).convert();
+            self.stream_key = self.ordered_update(val, self.stream_key);
+            input = rest;
+        }
+        if !input.is_empty() {
+            self.carry[..input.len()].copy_from_slice(input);
+            self.carry_len = input.len() as u8;
+        }
+    }
+
+    /// The value of `buffer` after folding in any pending `write_streaming` state
+    /// (total length and final partial block). This mirrors the *shape* of the
+    /// one-shot `write` path's own finalize step (fold length, then one closing
+    /// round), not its output - `write` and `write_streaming` produce different
+    /// hashes for the same bytes and aren't interchangeable. A no-op when only the
+    /// one-shot `write` has been used, which keeps `stable_with_keys`'s frozen output
+    /// untouched.
+    #[inline(always)]
+    fn pre_finalize_buffer(&self) -> u64 {
+        if self.stream_len == 0 && self.carry_len == 0 {
+            return self.buffer;
+        }
+        let mut buffer = self.buffer.wrapping_add(self.stream_len);
+        if self.carry_len > 0 {
+            let mut padded = [0u8; 8];
+            padded[..self.carry_len as usize].copy_from_slice(&self.carry[..self.carry_len as usize]);
+            let val: u64 = padded.convert();
+            buffer = buffer.wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE) ^ val;
+        }
+        buffer
+    }
 }
 
 /// Provides methods to hash all of the primitive types.
@@ -141,17 +237,125 @@ impl Hasher for AHasher {
             }
         }
     }
+
     #[inline]
     fn finish(&self) -> u64 {
-        self.buffer.wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE)
+        self.pre_finalize_buffer().wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE)
+    }
+}
+
+
+/// A variant of [AHasher] that produces the same `finish()` value regardless of the
+/// host's byte order, by reading every multi-byte chunk with explicit `from_le_bytes`
+/// calls instead of [Convert]'s native-endian reinterpretation. On little-endian
+/// targets this produces identical output to [AHasher].
+#[derive(Debug, Clone)]
+pub struct PortableAHasher {
+    buffer: u64
+}
+
+impl PortableAHasher {
+    /// Creates a new hasher keyed to the provided keys.
+    #[inline]
+    pub fn new_with_keys(key0: u64, key1: u64) -> PortableAHasher {
+        PortableAHasher { buffer: key0 ^ key1.rotate_left(ROT) }
+    }
+
+    #[inline(always)]
+    fn update(&mut self, new_data: u64) {
+        let existing = self.buffer.wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE);
+        self.buffer = existing ^ new_data;
+    }
+
+    #[inline(always)]
+    fn ordered_update(&mut self, new_data: u64, key: u64) -> u64 {
+        self.buffer ^= (new_data ^ key).wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE);
+        key.wrapping_add(INCREMENT)
     }
 }
 
+impl Hasher for PortableAHasher {
+
+    #[inline]
+    fn write_u8(&mut self, i: u8) {
+        self.update(i as u64);
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.update(i as u64);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.update(i as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.update(i);
+    }
+
+    #[inline]
+    fn write_u128(&mut self, i: u128) {
+        let bytes = i.to_le_bytes();
+        self.update(u64::from_le_bytes(*array_ref!(bytes, 0, 8)));
+        self.update(u64::from_le_bytes(*array_ref!(bytes, 8, 8)));
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write(&mut self, input: &[u8]) {
+        let mut data = input;
+        let length = data.len() as u64;
+        //Needs to be an add rather than an xor because otherwise it could be canceled with carefully formed input.
+        self.buffer = self.buffer.wrapping_add(length);
+        //A 'binary search' on sizes reduces the number of comparisons.
+        if data.len() > 8 {
+            let mut key: u64 = self.buffer;
+            while data.len() > 16 {
+                let (block, rest) = data.split_at(8);
+                let val = u64::from_le_bytes(*array_ref!(block, 0, 8));
+                key = self.ordered_update(val, key);
+                data = rest;
+            }
+            let val = u64::from_le_bytes(*array_ref!(data, 0, 8));
+            self.ordered_update(val, key);
+            let val = u64::from_le_bytes(*array_ref!(data, data.len()-8, 8));
+            self.update(val);
+        } else {
+            if data.len() >= 2 {
+                if data.len() >= 4 {
+                    let lo = u32::from_le_bytes(*array_ref!(data, 0, 4));
+                    let hi = u32::from_le_bytes(*array_ref!(data, data.len()-4, 4));
+                    self.update(lo as u64 | (hi as u64) << 32);
+                } else {
+                    let lo = u16::from_le_bytes(*array_ref!(data, 0, 2));
+                    let hi = u16::from_le_bytes(*array_ref!(data, data.len()-2, 2));
+                    self.update((lo as u32 | (hi as u32) << 16) as u64);
+                }
+            } else {
+                if data.len() >= 1 {
+                    self.update(data[0] as u64);
+                }
+            }
+        }
+    }
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.buffer.wrapping_mul(MULTIPLE).rotate_left(ROT).wrapping_mul(MULTIPLE)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use crate::convert::Convert;
     use crate::fallback_hash::*;
+    use std::hash::Hasher;
 
     #[test]
     fn test_hash() {
@@ -174,4 +378,153 @@ mod tests {
         let bytes: u64 = as_array!(input, 8).convert();
         assert_eq!(bytes, 0x6464646464646464);
     }
+
+    #[test]
+    fn test_finish128() {
+        let mut hasher = AHasher::new_with_keys(123, 456);
+        hasher.write(b"The quick brown fox jumps over the lazy dog");
+        let combined = hasher.finish128();
+        assert_eq!(combined as u64, hasher.finish());
+        assert_ne!((combined >> 64) as u64, combined as u64);
+    }
+
+    #[test]
+    fn test_streaming_matches_chunk_boundaries() {
+        let mut one_shot = AHasher::new_with_keys(123, 456);
+        one_shot.write_streaming(b"abcde");
+
+        let mut chunked = AHasher::new_with_keys(123, 456);
+        chunked.write_streaming(b"abc");
+        chunked.write_streaming(b"de");
+
+        let mut byte_at_a_time = AHasher::new_with_keys(123, 456);
+        for b in b"abcde" {
+            byte_at_a_time.write_streaming(&[*b]);
+        }
+
+        assert_eq!(one_shot.finish(), chunked.finish());
+        assert_eq!(one_shot.finish(), byte_at_a_time.finish());
+    }
+
+    #[test]
+    fn test_portable_hash() {
+        let mut hasher = PortableAHasher::new_with_keys(0,0);
+        let value: u64 = 1 << 32;
+        hasher.update(value);
+        let result = hasher.buffer;
+        let mut hasher = PortableAHasher::new_with_keys(0,0);
+        let value2: u64 = 1;
+        hasher.update(value2);
+        let result2 = hasher.buffer;
+        assert_ne!(result, result2);
+    }
+
+    #[test]
+    fn test_portable_matches_native_on_little_endian() {
+        #[cfg(target_endian = "little")]
+        {
+            let mut native = AHasher::new_with_keys(123, 456);
+            native.write(b"The quick brown fox jumps over the lazy dog");
+            let mut portable = PortableAHasher::new_with_keys(123, 456);
+            portable.write(b"The quick brown fox jumps over the lazy dog");
+            assert_eq!(native.finish(), portable.finish());
+        }
+    }
+
+    /// Pins `write_u16`/`write_u32`/`write_u64`/`write_u128` to feeding `update` the
+    /// plain numeric value (and, for `write_u128`, the numerically-correct high/low
+    /// split), rather than a byte-swapped one. Using `i.to_le()` here instead would be
+    /// a no-op on this (little-endian) test host, so it wouldn't be caught by a test
+    /// that just runs on this host and checks for a fixed expected value - instead this
+    /// compares against the matching width's `swap_bytes()`, which is exactly what `to_le()` would
+    /// reduce to on a big-endian host, and asserts the two disagree whenever the value
+    /// isn't byte-palindromic. That way a regression back to `to_le()` fails here
+    /// without needing an actual big-endian machine to run on.
+    #[test]
+    fn test_portable_write_u_methods_are_endian_independent() {
+        let mut hasher = PortableAHasher::new_with_keys(0, 0);
+        hasher.write_u16(0x1234);
+        let mut reference = PortableAHasher::new_with_keys(0, 0);
+        reference.update(0x1234u64);
+        assert_eq!(hasher.buffer, reference.buffer);
+        let mut would_be_be = PortableAHasher::new_with_keys(0, 0);
+        would_be_be.update(0x1234u16.swap_bytes() as u64);
+        assert_ne!(hasher.buffer, would_be_be.buffer);
+
+        let mut hasher = PortableAHasher::new_with_keys(0, 0);
+        hasher.write_u32(0x12345678);
+        let mut reference = PortableAHasher::new_with_keys(0, 0);
+        reference.update(0x12345678u64);
+        assert_eq!(hasher.buffer, reference.buffer);
+        let mut would_be_be = PortableAHasher::new_with_keys(0, 0);
+        would_be_be.update(0x12345678u32.swap_bytes() as u64);
+        assert_ne!(hasher.buffer, would_be_be.buffer);
+
+        let mut hasher = PortableAHasher::new_with_keys(0, 0);
+        hasher.write_u64(0x0123456789abcdef);
+        let mut reference = PortableAHasher::new_with_keys(0, 0);
+        reference.update(0x0123456789abcdef);
+        assert_eq!(hasher.buffer, reference.buffer);
+        let mut would_be_be = PortableAHasher::new_with_keys(0, 0);
+        would_be_be.update(0x0123456789abcdefu64.swap_bytes());
+        assert_ne!(hasher.buffer, would_be_be.buffer);
+
+        let mut hasher = PortableAHasher::new_with_keys(0, 0);
+        hasher.write_u128(0x0123456789abcdef_fedcba9876543210);
+        let mut reference = PortableAHasher::new_with_keys(0, 0);
+        reference.update(0xfedcba9876543210u64);
+        reference.update(0x0123456789abcdefu64);
+        assert_eq!(hasher.buffer, reference.buffer);
+        let mut would_be_be = PortableAHasher::new_with_keys(0, 0);
+        would_be_be.update(0xfedcba9876543210u64.swap_bytes());
+        would_be_be.update(0x0123456789abcdefu64.swap_bytes());
+        assert_ne!(hasher.buffer, would_be_be.buffer);
+    }
+}
+
+/// Hard-coded `(key0, key1, input) -> finish()` vectors for `AHasher::stable_with_keys`,
+/// at least one per length branch of `write` (0, 1, 2-3, 4-7, 8, 9-16, >16). Fails if any value
+/// ever changes.
+#[cfg(test)]
+mod compatibility {
+    use crate::fallback_hash::AHasher;
+    use std::hash::Hasher;
+
+    // (key0, key1, input, expected finish())
+    const VECTORS: &[(u64, u64, &[u8], u64)] = &[
+        (0, 0, b"", 0x0000000000000000),                                               // len 0
+        (0, 0, b"1", 0x62fb79ef12c24630),                                              // len 1
+        (123, 456, b"1", 0x4cb245d63d47799c),                                          // len 1
+        (0, 0, b"12", 0x5903389a43198b87),                                             // len 2-3
+        (123, 456, b"ab", 0x72cfe0083aef0af9),                                         // len 2-3
+        (0, 0, b"123", 0xf59143dd79dda950),                                            // len 2-3
+        (123, 456, b"abc", 0xe8b53b68a8171025),                                        // len 2-3
+        (0, 0, b"1234567", 0xc072db79e50b0676),                                        // len 4-7
+        (123, 456, b"abcdefg", 0xe82f36aeb99112c0),                                    // len 4-7
+        (0, 0, b"12345678", 0x85eb855a513cc897),                                       // len 8
+        (123, 456, b"abcdefgh", 0xe8a3da4d326299a4),                                   // len 8
+        (0, 0, b"123456789", 0xabbc25a8be552e24),                                      // len 9-16
+        (123, 456, b"abcdefghijklmnop", 0x063508ef5b741d4a),                           // len 9-16
+        (0, 0, b"0123456789ABCDEFG", 0x5732e4e24e33cd2d),                              // len >16
+        (
+            123,
+            456,
+            b"The quick brown fox jumps over the lazy dog",
+            0x94e7110f0e959d5d,
+        ), // len >16
+    ];
+
+    #[test]
+    fn stable_hash_matches_frozen_vectors() {
+        for &(key0, key1, input, expected) in VECTORS {
+            let mut hasher = AHasher::stable_with_keys(key0, key1);
+            hasher.write(input);
+            assert_eq!(
+                hasher.finish(),
+                expected,
+                "stable_with_keys({}, {}) over {:?} changed output; this breaks the on-disk compatibility guarantee",
+                key0, key1, input
+            );
+        }
+    }
 }